@@ -4,6 +4,8 @@
 //!
 //! Meltano [provides a document](https://hub.meltano.com/singer/spec/) with the various bits of
 //! the specification that are not in Singer's `SPEC.md` file.
+use std::io::{BufRead, Write};
+
 use serde::{Deserialize, Serialize};
 
 /// Messages sent over stdout or read from stdin.
@@ -19,6 +21,11 @@ pub enum Message {
         record: serde_json::Value,
         #[serde(with = "time::serde::rfc3339::option")]
         time_extracted: Option<time::OffsetDateTime>,
+        /// The version of a full-table replicated stream this record belongs to.
+        /// Stamped by taps doing full-table replication with hard-delete detection;
+        /// see [`Message::ActivateVersion`].
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<i64>,
     },
     /// Schema messages define the structure of the data sent in a record message.
     #[serde(rename = "SCHEMA")]
@@ -28,12 +35,56 @@ pub enum Message {
         key_properties: Vec<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         bookmark_properties: Option<Vec<String>>,
+        /// The version of a full-table replicated stream this schema describes.
+        /// See [`Message::ActivateVersion`].
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<i64>,
     },
     /// State messages contain any information that a tap is designed to persist.
     /// These are used to inform the target of the current place in the
     /// extraction of a data stream.
     #[serde(rename = "STATE")]
     State { value: serde_json::Value },
+    /// Batch messages point a target at externally written files instead of
+    /// inlining every row as a `RECORD`, which is far cheaper for high-volume taps.
+    #[serde(rename = "BATCH")]
+    Batch {
+        stream: String,
+        encoding: BatchEncoding,
+        /// URIs of the files making up this batch, in `encoding.format`.
+        manifest: Vec<String>,
+    },
+    /// Tells the target to delete any rows for `stream` from a prior load that
+    /// are not tagged with `version`. Used with full-table replication to express
+    /// hard deletes once a new full-table snapshot has been fully loaded.
+    #[serde(rename = "ACTIVATE_VERSION")]
+    ActivateVersion { stream: String, version: i64 },
+}
+
+/// Describes how the files listed in a [`Message::Batch`]'s `manifest` are encoded.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BatchEncoding {
+    pub format: BatchFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+/// The file format used to encode a batch's manifest files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchFormat {
+    /// Newline-delimited JSON, one record per line, matching the [`Message::Record`]
+    /// `record` field.
+    Jsonl,
+    /// [Apache Parquet](https://parquet.apache.org/).
+    Parquet,
+}
+
+/// The compression applied to a batch's manifest files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
 }
 
 /// A tap can periodically emit structured log messages containing metrics about read operations.
@@ -71,12 +122,45 @@ pub struct Metric {
     pub tags: serde_json::Value,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// The kind of measurement a [`Metric`] reports.
+///
+/// Deserialization is forward-compatible: a `type` this crate doesn't recognize
+/// (a future spec addition, or a tap-specific extension) is preserved as
+/// [`MetricType::Unknown`] rather than failing the whole message.
+#[derive(Clone, Debug)]
 pub enum MetricType {
-    #[serde(rename = "counter")]
     Counter,
-    #[serde(rename = "timer")]
     Timer,
+    /// A metric type not defined by this version of the spec. The original
+    /// string is preserved so re-serializing it round-trips losslessly.
+    Unknown(String),
+}
+
+impl Serialize for MetricType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(match self {
+            Self::Counter => "counter",
+            Self::Timer => "timer",
+            Self::Unknown(value) => value,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for MetricType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Ok(match value.as_str() {
+            "counter" => Self::Counter,
+            "timer" => Self::Timer,
+            _ => Self::Unknown(value),
+        })
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -86,6 +170,132 @@ pub enum MetricValue {
     Float(f64),
 }
 
+impl MetricValue {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Self::Integer(value) => *value as f64,
+            Self::Float(value) => *value,
+        }
+    }
+}
+
+impl Metric {
+    /// Extracts and parses a [`Metric`] from a tap log line in the
+    /// `INFO METRIC: <metric-json>` format, tolerating a leading timestamp or
+    /// other log-prefix text before the marker.
+    ///
+    /// Returns `None` if the line doesn't contain the `INFO METRIC:` marker at
+    /// all. Returns `Some(Err(_))` if the marker is present but the trailing
+    /// text isn't a valid [`Metric`].
+    pub fn parse_log_line(line: &str) -> Option<Result<Metric, serde_json::Error>> {
+        const MARKER: &str = "INFO METRIC:";
+        let index = line.find(MARKER)?;
+        let json = line[index + MARKER.len()..].trim();
+        Some(serde_json::from_str(json))
+    }
+
+    /// Formats this metric the way a tap writes it to its logs.
+    pub fn to_log_line(&self) -> String {
+        format!(
+            "INFO METRIC: {}",
+            serde_json::to_string(self).expect("Metric always serializes to JSON")
+        )
+    }
+}
+
+/// Running totals for a `timer` metric.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimerStats {
+    pub count: u64,
+    pub total: f64,
+}
+
+impl TimerStats {
+    /// The mean of all observed durations, or `0.0` if none have been recorded.
+    pub fn average(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total / self.count as f64
+        }
+    }
+}
+
+/// Aggregates a stream of [`Metric`]s, summing `counter` values and tracking
+/// `timer` totals/averages, grouped by metric name and (optionally) a tag key.
+///
+/// Lets a consumer watching a tap's `INFO METRIC:` log lines compute running
+/// throughput without writing its own line-scraping logic.
+pub struct MetricAggregator {
+    tag_key: Option<String>,
+    counters: std::collections::BTreeMap<(String, Option<String>), f64>,
+    timers: std::collections::BTreeMap<(String, Option<String>), TimerStats>,
+}
+
+impl MetricAggregator {
+    /// Creates an aggregator that groups metrics by name alone.
+    pub fn new() -> Self {
+        Self {
+            tag_key: None,
+            counters: Default::default(),
+            timers: Default::default(),
+        }
+    }
+
+    /// Creates an aggregator that further groups metrics by the string value of
+    /// `tag_key` in their `tags` object. Metrics missing the tag are grouped
+    /// under `None`.
+    pub fn with_tag_key(tag_key: impl Into<String>) -> Self {
+        Self {
+            tag_key: Some(tag_key.into()),
+            counters: Default::default(),
+            timers: Default::default(),
+        }
+    }
+
+    fn tag_value(&self, metric: &Metric) -> Option<String> {
+        let tag_key = self.tag_key.as_ref()?;
+        metric.tags.get(tag_key)?.as_str().map(str::to_string)
+    }
+
+    /// Folds `metric` into the running totals.
+    pub fn observe(&mut self, metric: &Metric) {
+        let key = (metric.metric.clone(), self.tag_value(metric));
+        match &metric.metric_type {
+            MetricType::Counter => *self.counters.entry(key).or_default() += metric.value.as_f64(),
+            MetricType::Timer => {
+                let stats = self.timers.entry(key).or_default();
+                stats.count += 1;
+                stats.total += metric.value.as_f64();
+            }
+            MetricType::Unknown(_) => {}
+        }
+    }
+
+    /// The running sum for `metric` (and `tag`, if this aggregator groups by tag).
+    pub fn counter(&self, metric: &str, tag: Option<&str>) -> f64 {
+        self.counters
+            .get(&(metric.to_string(), tag.map(str::to_string)))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The running totals/average for `metric` (and `tag`, if this aggregator
+    /// groups by tag).
+    pub fn timer(&self, metric: &str, tag: Option<&str>) -> TimerStats {
+        self.timers
+            .get(&(metric.to_string(), tag.map(str::to_string)))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for MetricAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Stream {
     /// The primary identifier of the stream as it will be passed to the target.
@@ -114,14 +324,49 @@ impl Default for Include {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// How a tap replicates a stream.
+///
+/// Deserialization is forward-compatible: a `replication-method` this crate
+/// doesn't recognize (a future spec addition, or a tap-specific extension) is
+/// preserved as [`ReplicationMethod::Unknown`] rather than failing the whole
+/// message.
+#[derive(Clone, Debug)]
 pub enum ReplicationMethod {
-    #[serde(rename = "FULL_TABLE")]
     FullTable,
-    #[serde(rename = "INCREMENTAL")]
     Incremental,
-    #[serde(rename = "LOG_BASED")]
     LogBased,
+    /// A replication method not defined by this version of the spec. The
+    /// original string is preserved so re-serializing it round-trips losslessly.
+    Unknown(String),
+}
+
+impl Serialize for ReplicationMethod {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(match self {
+            Self::FullTable => "FULL_TABLE",
+            Self::Incremental => "INCREMENTAL",
+            Self::LogBased => "LOG_BASED",
+            Self::Unknown(value) => value,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for ReplicationMethod {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Ok(match value.as_str() {
+            "FULL_TABLE" => Self::FullTable,
+            "INCREMENTAL" => Self::Incremental,
+            "LOG_BASED" => Self::LogBased,
+            _ => Self::Unknown(value),
+        })
+    }
 }
 
 /// Metadata that is provided to the tap and is not discoverable from the source.
@@ -188,6 +433,321 @@ pub struct Catalog {
     pub streams: Vec<Stream>,
 }
 
+/// A single constraint violation found by [`SchemaValidator::validate`] or
+/// [`SchemaValidator::validate_key_properties`].
+#[derive(Clone, Debug)]
+pub struct ValidationError {
+    /// JSON Pointer path (relative to the record) to the value that failed validation.
+    pub path: String,
+    /// A human-readable description of the violated constraint.
+    pub message: String,
+}
+
+/// Errors that can occur while constructing a [`SchemaValidator`].
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaValidatorError {
+    /// The `message` passed to [`SchemaValidator::new`] was not a [`Message::Schema`].
+    #[error("message is not a SCHEMA message")]
+    NotASchemaMessage,
+    /// The `schema` value on a [`Message::Schema`] was not a valid JSON Schema.
+    #[error("invalid JSON Schema: {0}")]
+    InvalidSchema(String),
+}
+
+/// Validates [`Message::Record`] payloads against the JSON Schema declared by a
+/// [`Message::Schema`] message, so a tap can catch schema drift before shipping
+/// bad data downstream.
+pub struct SchemaValidator {
+    validator: jsonschema::Validator,
+    key_properties: Vec<String>,
+}
+
+impl SchemaValidator {
+    /// Compiles the JSON Schema carried by `message` and remembers its
+    /// `key_properties` so records can be validated against it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SchemaValidatorError::NotASchemaMessage`] if `message` isn't a
+    /// [`Message::Schema`], or [`SchemaValidatorError::InvalidSchema`] if its
+    /// `schema` value isn't a valid JSON Schema.
+    pub fn new(message: &Message) -> Result<Self, SchemaValidatorError> {
+        let Message::Schema {
+            schema,
+            key_properties,
+            ..
+        } = message
+        else {
+            return Err(SchemaValidatorError::NotASchemaMessage);
+        };
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|err| SchemaValidatorError::InvalidSchema(err.to_string()))?;
+        Ok(Self {
+            validator,
+            key_properties: key_properties.clone(),
+        })
+    }
+
+    /// Validates `record` against the compiled schema, collecting every
+    /// constraint violation rather than stopping at the first.
+    pub fn validate(&self, record: &serde_json::Value) -> Result<(), Vec<ValidationError>> {
+        let errors: Vec<ValidationError> = self
+            .validator
+            .iter_errors(record)
+            .map(|err| ValidationError {
+                path: err.instance_path.to_string(),
+                message: err.to_string(),
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Ensures every property in [`Schema::key_properties`](Message::Schema) is
+    /// present in `record` and not `null`.
+    pub fn validate_key_properties(
+        &self,
+        record: &serde_json::Value,
+    ) -> Result<(), Vec<ValidationError>> {
+        let errors: Vec<ValidationError> = self
+            .key_properties
+            .iter()
+            .filter_map(|key| {
+                let path = format!("/{}", key.replace('~', "~0").replace('/', "~1"));
+                match record.get(key) {
+                    None => Some(ValidationError {
+                        path,
+                        message: format!("key property `{key}` is missing"),
+                    }),
+                    Some(serde_json::Value::Null) => Some(ValidationError {
+                        path,
+                        message: format!("key property `{key}` is null"),
+                    }),
+                    Some(_) => None,
+                }
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Tracks the most recently seen [`SchemaValidator`] for each stream, so a
+/// pipeline can validate a live sequence of `SCHEMA` and `RECORD` messages.
+#[derive(Default)]
+pub struct StreamValidators {
+    validators: std::collections::BTreeMap<String, SchemaValidator>,
+}
+
+impl StreamValidators {
+    /// Creates an empty set of validators.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// If `message` is a [`Message::Schema`], compiles it and registers it as
+    /// the current validator for its stream.
+    pub fn observe(&mut self, message: &Message) -> Result<(), SchemaValidatorError> {
+        if let Message::Schema { stream, .. } = message {
+            let validator = SchemaValidator::new(message)?;
+            self.validators.insert(stream.clone(), validator);
+        }
+        Ok(())
+    }
+
+    /// Returns the validator currently registered for `stream`, if a `SCHEMA`
+    /// message has been observed for it.
+    pub fn get(&self, stream: &str) -> Option<&SchemaValidator> {
+        self.validators.get(stream)
+    }
+}
+
+/// The standard shape of a `STATE` message payload: one [`Bookmark`] per
+/// stream, plus which stream (if any) was mid-sync when the state was emitted.
+///
+/// This gives taps and targets a typed view of the Singer bookmark convention
+/// instead of hand-editing the raw [`Message::State`] JSON.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub bookmarks: std::collections::BTreeMap<String, Bookmark>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currently_syncing: Option<String>,
+    /// Tap-specific top-level keys that aren't part of the standard shape.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A single stream's replication bookmark.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Bookmark {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication_key_value: Option<serde_json::Value>,
+    /// Tap-specific bookmark keys that aren't part of the standard shape.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl State {
+    /// Returns the bookmark for `stream`, if one has been recorded.
+    pub fn get_bookmark(&self, stream: &str) -> Option<&Bookmark> {
+        self.bookmarks.get(stream)
+    }
+
+    /// Sets `stream`'s replication key and the value it last replicated up to,
+    /// creating the bookmark if one doesn't exist yet.
+    pub fn set_replication_key_value(
+        &mut self,
+        stream: &str,
+        key: impl Into<String>,
+        value: serde_json::Value,
+    ) {
+        let bookmark = self.bookmarks.entry(stream.to_string()).or_default();
+        bookmark.replication_key = Some(key.into());
+        bookmark.replication_key_value = Some(value);
+    }
+
+    /// Applies an incoming state delta on top of this state: `other`'s
+    /// `currently_syncing` replaces this one, and each of its bookmarks
+    /// overwrites (or adds) the bookmark for that stream.
+    pub fn merge(&mut self, other: State) {
+        self.currently_syncing = other.currently_syncing;
+        self.bookmarks.extend(other.bookmarks);
+        self.extra.extend(other.extra);
+    }
+}
+
+/// Errors that can occur while converting a [`Message`] into a typed [`State`].
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    /// The [`Message`] passed to [`State::try_from`] was not a [`Message::State`].
+    #[error("message is not a STATE message")]
+    NotAStateMessage,
+    /// The `STATE` message's `value` did not match the standard [`State`] shape.
+    #[error("invalid STATE payload: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+}
+
+impl TryFrom<&Message> for State {
+    type Error = StateError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let Message::State { value } = message else {
+            return Err(StateError::NotAStateMessage);
+        };
+        Ok(serde_json::from_value(value.clone())?)
+    }
+}
+
+impl From<State> for Message {
+    fn from(state: State) -> Self {
+        Message::State {
+            value: serde_json::to_value(state)
+                .expect("State always serializes to a JSON object"),
+        }
+    }
+}
+
+/// Errors produced while reading or writing a stream of newline-delimited [`Message`]s.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// An I/O error occurred while reading from or writing to the underlying stream.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A line was read successfully but did not contain a valid [`Message`].
+    #[error("invalid message on line {line}: {source}")]
+    InvalidMessage {
+        /// The 1-indexed line number the malformed message was read from.
+        line: usize,
+        source: serde_json::Error,
+    },
+    /// A [`Message`] could not be serialized to JSON.
+    #[error("failed to serialize message: {0}")]
+    Serialize(serde_json::Error),
+}
+
+/// Reads [`Message`]s from a stream, one JSON object per line, per the SPEC's
+/// requirement that each message "MUST be serialized to JSON on a single line".
+///
+/// Blank lines are skipped. Iteration stops (yields `None`) once the underlying
+/// reader reaches EOF.
+pub struct MessageReader<R> {
+    reader: R,
+    line: usize,
+}
+
+impl<R: BufRead> MessageReader<R> {
+    /// Wraps `reader`, reading one [`Message`] per non-empty line.
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for MessageReader<R> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            match self.reader.read_line(&mut buf) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.line += 1;
+                    let line = buf.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    return Some(serde_json::from_str(line).map_err(|source| {
+                        Error::InvalidMessage {
+                            line: self.line,
+                            source,
+                        }
+                    }));
+                }
+                Err(err) => return Some(Err(Error::Io(err))),
+            }
+        }
+    }
+}
+
+/// Writes [`Message`]s to a stream, one JSON object per line.
+pub struct MessageWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// Wraps `writer`, serializing each [`Message`] passed to [`MessageWriter::write`]
+    /// onto its own line.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Serializes `message` to a single line of JSON and writes it, followed by a newline.
+    ///
+    /// Callers that need the target to observe the record promptly (rather than
+    /// buffered) should call [`MessageWriter::flush`] afterwards.
+    pub fn write(&mut self, message: &Message) -> Result<(), Error> {
+        let line = serde_json::to_string(message).map_err(Error::Serialize)?;
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush().map_err(Error::Io)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,4 +780,306 @@ mod tests {
             serde_json::to_string(&example_metric).unwrap()
         );
     }
+
+    #[test]
+    fn message_reader_reads_one_message_per_line() {
+        let input = "{\"type\":\"STATE\",\"value\":{\"a\":1}}\n\n{\"type\":\"STATE\",\"value\":{\"a\":2}}\n";
+        let mut reader = MessageReader::new(input.as_bytes());
+        assert!(matches!(reader.next(), Some(Ok(Message::State { .. }))));
+        assert!(matches!(reader.next(), Some(Ok(Message::State { .. }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn message_reader_reports_line_number_of_malformed_json() {
+        let input = "{\"type\":\"STATE\",\"value\":{}}\nnot json\n";
+        let mut reader = MessageReader::new(input.as_bytes());
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        match reader.next() {
+            Some(Err(Error::InvalidMessage { line, .. })) => assert_eq!(line, 2),
+            other => panic!("expected InvalidMessage on line 2, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn message_writer_writes_one_line_per_message() {
+        let mut buf = Vec::new();
+        let mut writer = MessageWriter::new(&mut buf);
+        writer
+            .write(&Message::State {
+                value: serde_json::json!({"a": 1}),
+            })
+            .unwrap();
+        writer.flush().unwrap();
+        assert_eq!(buf, b"{\"type\":\"STATE\",\"value\":{\"a\":1}}\n");
+    }
+
+    #[test]
+    fn metric_type_falls_back_to_unknown() {
+        let metric_type: MetricType = serde_json::from_str(r#""gauge""#).unwrap();
+        assert!(matches!(metric_type, MetricType::Unknown(ref s) if s == "gauge"));
+        assert_eq!(serde_json::to_string(&metric_type).unwrap(), r#""gauge""#);
+    }
+
+    #[test]
+    fn replication_method_falls_back_to_unknown() {
+        let method: ReplicationMethod = serde_json::from_str(r#""LOG_BASED""#).unwrap();
+        assert!(matches!(method, ReplicationMethod::LogBased));
+
+        let method: ReplicationMethod = serde_json::from_str(r#""MELTANO_EXTENSION""#).unwrap();
+        assert!(matches!(method, ReplicationMethod::Unknown(ref s) if s == "MELTANO_EXTENSION"));
+        assert_eq!(
+            serde_json::to_string(&method).unwrap(),
+            r#""MELTANO_EXTENSION""#
+        );
+    }
+
+    #[test]
+    fn batch_serialization() {
+        let result = serde_json::to_string(&Message::Batch {
+            stream: "users".to_string(),
+            encoding: BatchEncoding {
+                format: BatchFormat::Jsonl,
+                compression: Some(Compression::Gzip),
+            },
+            manifest: vec!["s3://bucket/batch-0.jsonl.gz".to_string()],
+        })
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"{"type":"BATCH","stream":"users","encoding":{"format":"jsonl","compression":"gzip"},"manifest":["s3://bucket/batch-0.jsonl.gz"]}"#
+        );
+    }
+
+    #[test]
+    fn activate_version_serialization() {
+        let result = serde_json::to_string(&Message::ActivateVersion {
+            stream: "users".to_string(),
+            version: 1,
+        })
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"{"type":"ACTIVATE_VERSION","stream":"users","version":1}"#
+        );
+    }
+
+    #[test]
+    fn record_version_omitted_when_none() {
+        let result = serde_json::to_string(&Message::Record {
+            stream: "users".to_string(),
+            record: serde_json::json!({}),
+            time_extracted: None,
+            version: None,
+        })
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"{"type":"RECORD","stream":"users","record":{},"time_extracted":null}"#
+        );
+    }
+
+    fn users_schema_message() -> Message {
+        Message::Schema {
+            stream: "users".to_string(),
+            schema: serde_json::json!({
+                "type": "object",
+                "properties": { "id": { "type": "integer" } },
+                "required": ["id"],
+            }),
+            key_properties: vec!["id".to_string()],
+            bookmark_properties: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn schema_validator_accepts_matching_record() {
+        let validator = SchemaValidator::new(&users_schema_message()).unwrap();
+        assert!(validator.validate(&serde_json::json!({"id": 1})).is_ok());
+        assert!(validator.validate_key_properties(&serde_json::json!({"id": 1})).is_ok());
+    }
+
+    #[test]
+    fn schema_validator_rejects_non_matching_record() {
+        let validator = SchemaValidator::new(&users_schema_message()).unwrap();
+        assert!(validator
+            .validate(&serde_json::json!({"id": "not an integer"}))
+            .is_err());
+        assert!(validator
+            .validate_key_properties(&serde_json::json!({"name": "alice"}))
+            .is_err());
+    }
+
+    #[test]
+    fn stream_validators_tracks_latest_schema_per_stream() {
+        let mut validators = StreamValidators::new();
+        validators.observe(&users_schema_message()).unwrap();
+        assert!(validators.get("users").is_some());
+        assert!(validators.get("orders").is_none());
+
+        validators
+            .observe(&Message::Schema {
+                stream: "users".to_string(),
+                schema: serde_json::json!({"type": "object"}),
+                key_properties: vec!["email".to_string()],
+                bookmark_properties: None,
+                version: None,
+            })
+            .unwrap();
+        assert!(validators
+            .get("users")
+            .unwrap()
+            .validate_key_properties(&serde_json::json!({"id": 1}))
+            .is_err());
+    }
+
+    #[test]
+    fn state_round_trips_through_message() {
+        let mut state = State::default();
+        state.set_replication_key_value("users", "updated_at", serde_json::json!("2024-01-01"));
+        state.currently_syncing = Some("users".to_string());
+
+        let message: Message = state.clone().into();
+        let recovered = State::try_from(&message).unwrap();
+        assert_eq!(
+            recovered.get_bookmark("users").unwrap().replication_key_value,
+            Some(serde_json::json!("2024-01-01"))
+        );
+        assert_eq!(recovered.currently_syncing, Some("users".to_string()));
+    }
+
+    #[test]
+    fn state_round_trip_preserves_unknown_top_level_keys() {
+        let message = Message::State {
+            value: serde_json::json!({
+                "bookmarks": {},
+                "currently_syncing": null,
+                "tap_custom_extra": "keep me",
+            }),
+        };
+        let state = State::try_from(&message).unwrap();
+        let recovered: Message = state.into();
+        let Message::State { value } = recovered else {
+            panic!("expected a STATE message");
+        };
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "tap_custom_extra": "keep me",
+            })
+        );
+    }
+
+    #[test]
+    fn state_try_from_rejects_non_state_message() {
+        let message = Message::ActivateVersion {
+            stream: "users".to_string(),
+            version: 1,
+        };
+        assert!(matches!(
+            State::try_from(&message),
+            Err(StateError::NotAStateMessage)
+        ));
+    }
+
+    #[test]
+    fn state_merge_overwrites_and_adds_bookmarks() {
+        let mut state = State::default();
+        state.set_replication_key_value("users", "updated_at", serde_json::json!(1));
+
+        let mut delta = State::default();
+        delta.set_replication_key_value("users", "updated_at", serde_json::json!(2));
+        delta.set_replication_key_value("orders", "id", serde_json::json!(5));
+
+        state.merge(delta);
+
+        assert_eq!(
+            state.get_bookmark("users").unwrap().replication_key_value,
+            Some(serde_json::json!(2))
+        );
+        assert_eq!(
+            state.get_bookmark("orders").unwrap().replication_key_value,
+            Some(serde_json::json!(5))
+        );
+    }
+
+    #[test]
+    fn metric_parse_log_line_tolerates_leading_timestamp() {
+        let line = r#"2024-01-01T00:00:00 INFO METRIC: {"type":"counter","metric":"records","value":42,"tags":{}}"#;
+        let metric = Metric::parse_log_line(line).unwrap().unwrap();
+        assert_eq!(metric.metric, "records");
+        assert!(matches!(metric.value, MetricValue::Integer(42)));
+    }
+
+    #[test]
+    fn metric_parse_log_line_returns_none_without_marker() {
+        assert!(Metric::parse_log_line("just a regular log line").is_none());
+    }
+
+    #[test]
+    fn metric_to_log_line_round_trips_with_parse_log_line() {
+        let metric = Metric {
+            metric_type: MetricType::Timer,
+            metric: "http_request_duration".to_string(),
+            value: MetricValue::Float(1.5),
+            tags: serde_json::json!({}),
+        };
+        let parsed = Metric::parse_log_line(&metric.to_log_line()).unwrap().unwrap();
+        assert_eq!(parsed.metric, metric.metric);
+    }
+
+    #[test]
+    fn metric_aggregator_sums_counters_and_averages_timers() {
+        let mut aggregator = MetricAggregator::new();
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Counter,
+            metric: "records".to_string(),
+            value: MetricValue::Integer(10),
+            tags: serde_json::json!({}),
+        });
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Counter,
+            metric: "records".to_string(),
+            value: MetricValue::Integer(5),
+            tags: serde_json::json!({}),
+        });
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Timer,
+            metric: "http_request_duration".to_string(),
+            value: MetricValue::Float(2.0),
+            tags: serde_json::json!({}),
+        });
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Timer,
+            metric: "http_request_duration".to_string(),
+            value: MetricValue::Float(4.0),
+            tags: serde_json::json!({}),
+        });
+
+        assert_eq!(aggregator.counter("records", None), 15.0);
+        let timer = aggregator.timer("http_request_duration", None);
+        assert_eq!(timer.count, 2);
+        assert_eq!(timer.average(), 3.0);
+    }
+
+    #[test]
+    fn metric_aggregator_groups_by_tag_key() {
+        let mut aggregator = MetricAggregator::with_tag_key("stream");
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Counter,
+            metric: "records".to_string(),
+            value: MetricValue::Integer(3),
+            tags: serde_json::json!({"stream": "users"}),
+        });
+        aggregator.observe(&Metric {
+            metric_type: MetricType::Counter,
+            metric: "records".to_string(),
+            value: MetricValue::Integer(7),
+            tags: serde_json::json!({"stream": "orders"}),
+        });
+
+        assert_eq!(aggregator.counter("records", Some("users")), 3.0);
+        assert_eq!(aggregator.counter("records", Some("orders")), 7.0);
+    }
 }